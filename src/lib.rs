@@ -1,11 +1,370 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![doc = include_str!(concat!("../", env!("CARGO_PKG_README")))]
 
 use core::{arch::asm, mem::size_of, ops::Range};
 
-/// The value used to paint the stack.
+/// The seed used to derive the address-specific paint pattern written by
+/// [StackRegion::paint] (see [paint_value_for]).
+///
+/// Kept under its original name, as a single constant, for backward compatibility with
+/// code that referenced it when the whole stack was painted with this one value.
 pub const STACK_PAINT_VALUE: u32 = 0xCCCC_CCCC;
 
+/// The left-rotate amount applied to a word's address when deriving its expected paint
+/// pattern in [paint_value_for].
+const PAINT_ROTATE: u32 = 7;
+
+/// Shift distance that zero-extends a truncated-to-32-bit value back out to the full
+/// register width, used by [paint_words] and [first_unpainted] to mirror
+/// [paint_value_for]'s `addr as u32` truncation before rotating.
+///
+/// `0` on RV32, where every address is already 32 bits. `32` on RV64, where `slli` by
+/// this amount followed by `srli` by the same amount zeroes the upper half of the
+/// register, the asm equivalent of `addr as u32`.
+#[cfg(target_pointer_width = "32")]
+const ADDR_TRUNC_SHIFT: u32 = 0;
+#[cfg(target_pointer_width = "64")]
+const ADDR_TRUNC_SHIFT: u32 = 32;
+
+/// Computes the paint pattern expected at address `addr` when painted by
+/// [StackRegion::paint].
+///
+/// Rather than writing the single constant [STACK_PAINT_VALUE] to every free word
+/// (which the scanners could mistake for a run of unlucky-but-legitimate live data),
+/// each word is painted with a value tied to its own address: `SEED ^
+/// rotate_left(addr, k)`. Consecutive painted words therefore hold different values, so
+/// real stack data would need to coincidentally reproduce an address-dependent sequence
+/// rather than a single repeated constant, which is astronomically less likely over a
+/// run of any length.
+#[inline]
+pub fn paint_value_for(addr: *const u32) -> u32 {
+    STACK_PAINT_VALUE ^ (addr as u32).rotate_left(PAINT_ROTATE)
+}
+
+/// A stack-shaped region of memory that can be painted and measured on its own,
+/// independent of the calling hart's own live stack.
+///
+/// This is the building block behind [repaint_stack], [stack_painted] and friends, but it is also useful on its
+/// own: a cooperative scheduler or green-thread runtime that carves individual task
+/// stacks out of a static buffer can wrap each task's slice in a [StackRegion] and reuse
+/// all of the painting/measurement logic below, passing in the task's saved stack
+/// pointer (from its context block) instead of the CPU's live `sp`.
+///
+/// Note: like the range returned by [stack], this range runs in reverse (`start` is the
+/// high address, `end` is the low address), so it is technically empty because
+/// `start >= end`.
+///
+/// *Important*: `range.end` represents one past the last valid word in the region, so do
+/// not attempt to write to it, as for the hart stack that would overwrite the start of
+/// another hart's stack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackRegion {
+    range: Range<*mut u32>,
+}
+
+impl StackRegion {
+    /// Wrap a raw stack range (as returned by [stack]) in a [StackRegion].
+    ///
+    /// # Safety
+    ///
+    /// `range` must describe a region laid out like [stack] (`start` the high address,
+    /// `end` one past the lowest valid word), and every method called on the resulting
+    /// [StackRegion] must be passed a `current` that genuinely lies within `range`, and
+    /// that exclusively owns the range for the duration of the call (no other context
+    /// reading or writing it concurrently), or those methods' pointer arithmetic and
+    /// memory accesses are unsound.
+    #[inline]
+    pub const unsafe fn new(range: Range<*mut u32>) -> Self {
+        Self { range }
+    }
+
+    /// The [Range] backing this region.
+    #[inline]
+    pub fn range(&self) -> Range<*mut u32> {
+        self.range.clone()
+    }
+
+    /// The number of bytes reserved for this region.
+    ///
+    /// # Safety
+    ///
+    /// This region must have been built from a valid range, per [new](Self::new).
+    #[inline]
+    pub unsafe fn size(&self) -> usize {
+        // SAFETY: start >= end, provided this region was built correctly (see `new`).
+        unsafe { self.range.start.byte_offset_from_unsigned(self.range.end) }
+    }
+
+    /// The number of bytes of this region that are in use, given `current` as the
+    /// region's live stack pointer (the CPU's `sp` for an active stack, or the saved SP
+    /// from a task's context block for an inactive one).
+    ///
+    /// # Safety
+    ///
+    /// `current` must genuinely lie within this region, per [new](Self::new).
+    #[inline]
+    pub unsafe fn in_use(&self, current: *mut u32) -> usize {
+        // SAFETY: start >= current, provided `current` truly belongs to this region.
+        unsafe { self.range.start.byte_offset_from_unsigned(current) }
+    }
+
+    /// The number of bytes of this region that are currently free, given `current` as
+    /// the region's live stack pointer.
+    ///
+    /// If the region has overflowed, this function returns 0.
+    ///
+    /// # Safety
+    ///
+    /// `current` must genuinely lie within this region, per [new](Self::new).
+    #[inline]
+    pub unsafe fn free(&self, current: *mut u32) -> usize {
+        // SAFETY: forwarded from this function's own contract.
+        unsafe { self.size().saturating_sub(self.in_use(current)) }
+    }
+
+    /// Paint the part of this region that is not in use, given `current` as the
+    /// region's live stack pointer.
+    ///
+    /// **Note:** this can take some time, and an ISR could possibly interrupt this
+    /// process, dirtying up your freshly painted stack.
+    /// If you wish to prevent this, run this inside a critical section using
+    /// `riscv::interrupt::free`.
+    ///
+    /// Runs in *O(n)* where *n* is the size of the region.
+    /// This function is inefficient in the sense that it repaints the entire free part
+    /// of the region, even the parts that still hold their expected [paint_value_for]
+    /// pattern.
+    ///
+    /// # Safety
+    ///
+    /// `current` must genuinely lie within this region, and exclusively own it for the
+    /// duration of the call, per [new](Self::new).
+    #[inline(never)]
+    pub unsafe fn paint(&self, current: *mut u32) {
+        // SAFETY: `current` must point somewhere within this region's bounds.
+        unsafe { paint_words(self.range.end.add(1), current) };
+    }
+
+    /// Finds the number of bytes that have not been overwritten in this region since the
+    /// last [paint](Self::paint), given `current` as the region's live stack pointer.
+    ///
+    /// In other words: shows the worst case free stack space since this region was last
+    /// painted.
+    ///
+    /// This measurement can only ever be an ESTIMATE, and not a guarantee, as the amount
+    /// of stack can change immediately, even during an interrupt while we are measuring,
+    /// or by a devious user or compiler that re-paints the stack, obscuring the max
+    /// measured value. This measurement MUST NOT be used for load-bearing-safety
+    /// guarantees, only as a (generally accurate but non-guaranteed) measurement.
+    ///
+    /// Runs in *O(n)* where *n* is the size of the region.
+    ///
+    /// # Safety
+    ///
+    /// `current` must genuinely lie within this region, per [new](Self::new).
+    #[inline(never)]
+    pub unsafe fn painted_linear(&self, current: *mut u32) -> usize {
+        // SAFETY: As per the [rust reference], inline asm is allowed to look below the
+        // stack pointer. We read the values between the end of the region and
+        // `current`, which are all valid locations.
+        //
+        // In the case of interruption, there could be false negatives where we don't
+        // see stack that was used "behind" our cursor, however this is fine because we
+        // do not rely on this number for any safety-bearing contents, only as a metrics
+        // estimate.
+        //
+        // [rust reference]: https://doc.rust-lang.org/reference/inline-assembly.html#r-asm.rules.stack-below-sp
+        let res = unsafe { first_unpainted(self.range.end.add(1), current) };
+        // SAFETY: res >= self.range.end because we start at self.range.end
+        unsafe { res.byte_offset_from_unsigned(self.range.end) }
+    }
+
+    /// Finds the number of bytes that have not been overwritten in this region since the
+    /// last [paint](Self::paint) using binary search, given `current` as the region's
+    /// live stack pointer.
+    ///
+    /// In other words: shows the worst case free stack space since this region was last
+    /// painted.
+    ///
+    /// Uses binary search to find the point after which the region is written.
+    /// This will assume that the region is written in a consecutive fashion.
+    /// Writing somewhere out-of-order into the painted region will not be detected.
+    ///
+    /// Runs in *O(log(n))* where *n* is the size of the region.
+    ///
+    /// Since each word's expected value is tied to its own address (see
+    /// [paint_value_for]), a run of live data matching the pattern is astronomically
+    /// unlikely, which is what makes trusting the consecutive-run assumption here safe.
+    ///
+    /// # Safety
+    ///
+    /// This function aliases the region's memory, which is considered to be Undefined
+    /// Behaviour (and, for an inactive task stack, may alias memory another context
+    /// believes it exclusively owns). Do not use if you care about such things.
+    pub unsafe fn painted_binary(&self, current: *mut u32) -> usize {
+        let base = self.range.end.add(1);
+        let n = self.free(current) / size_of::<u32>();
+
+        // Manual partition_point over addresses rather than `[u32]::partition_point`,
+        // since the predicate needs each word's own address to compute its expected
+        // [paint_value_for] pattern, not just its value.
+        let mut lo = 0usize;
+        let mut hi = n;
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let ptr = unsafe { base.add(mid) };
+            // SAFETY: `ptr` is within `n` (`self.free(current)` bytes) words of `base`, all of
+            // which are valid locations in this region.
+            let word = unsafe { ptr.read() };
+            if word == paint_value_for(ptr) {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        lo * size_of::<u32>()
+    }
+}
+
+/// The [StackRegion] backing the current hart's stack.
+///
+/// If [install_stack_guard] has reserved this hart's [guard_slot] for the overflow
+/// sentinel, that word is excluded from the returned region, so painting and
+/// measurement through it never reads or clobbers the sentinel.
+#[inline]
+fn stack_region() -> StackRegion {
+    stack_region_for(hartid())
+}
+
+/// The [StackRegion] backing an arbitrary hart's stack, generalizing [stack_region] to a
+/// hart other than the current one.
+///
+/// Like [stack_region], excludes the guard slot from the returned region if
+/// [install_stack_guard] has reserved it for `hartid`. A `hartid >= `[MAX_HARTS] is
+/// treated the same as no guard ever having been installed for it (overflow guards are
+/// an opt-in, bounded-table feature; the underlying stack measurement below has no such
+/// bound), rather than panicking.
+#[inline]
+fn stack_region_for(hartid: usize) -> StackRegion {
+    let mut range = stack_for(hartid);
+    if guard_installed_for(hartid) {
+        // The guard slot is `range.end.add(1)`. Advancing `end` to there makes it one
+        // past the guard slot instead of one past the word below it, excluding the
+        // guard slot from every size/paint/scan computation that follows.
+        range.end = guard_slot(range.end);
+    }
+    // SAFETY: `range` (optionally shrunk by one word above to exclude the guard slot)
+    // is laid out exactly as [StackRegion::new] requires.
+    unsafe { StackRegion::new(range) }
+}
+
+/// The current hart's ID, as read from the `mhartid` CSR.
+#[inline]
+fn hartid() -> usize {
+    let hartid: usize;
+    // SAFETY: We are just reading from a CSR
+    unsafe { asm!("csrr {}, mhartid", out(reg) hartid) };
+    hartid
+}
+
+/// Paints every word in `[lo, hi)` with its address-specific [paint_value_for] pattern.
+///
+/// Shared by [StackRegion::paint] and [repaint_stack_incremental], which differ only in
+/// which sub-range of a region they need painted.
+///
+/// # Safety
+///
+/// `[lo, hi)` must lie within stack memory that is safe to write to (i.e. not currently
+/// in use).
+#[inline(never)]
+unsafe fn paint_words(lo: *mut u32, hi: *mut u32) {
+    // `{addr}` truncates `{ptr}` to its low 32 bits (a no-op on RV32), matching
+    // [paint_value_for]'s `addr as u32` so a store on RV64 agrees with a scan done in
+    // Rust. `{tmp}`/`{tmp2}` then compute `rotate_left(addr, PAINT_ROTATE)` as
+    // `(addr << k) | (addr >> (32 - k))`, since base RV32I has no rotate instruction.
+    unsafe {
+        asm!(
+            "0:",
+            "bgeu {ptr}, {hi}, 1f",
+            "slli {addr}, {ptr}, {trunc}",
+            "srli {addr}, {addr}, {trunc}",
+            "slli {tmp}, {addr}, {k}",
+            "srli {tmp2}, {addr}, {krev}",
+            "or {tmp}, {tmp}, {tmp2}",
+            "xor {tmp}, {tmp}, {seed}",
+            "sw {tmp}, 0({ptr})",
+            "addi {ptr}, {ptr}, 4",
+            "j 0b",
+            "1:",
+            ptr = inout(reg) lo => _,
+            hi = in(reg) hi,
+            addr = out(reg) _,
+            tmp = out(reg) _,
+            tmp2 = out(reg) _,
+            seed = in(reg) STACK_PAINT_VALUE,
+            k = const PAINT_ROTATE,
+            krev = const 32 - PAINT_ROTATE,
+            trunc = const ADDR_TRUNC_SHIFT,
+        )
+    };
+}
+
+/// Scans `[lo, hi)` for the first word that doesn't hold its address-specific
+/// [paint_value_for] pattern, returning that word's address (or `hi` if every word up to
+/// it is still painted).
+///
+/// Shared by [StackRegion::painted_linear] and [repaint_stack_incremental].
+///
+/// # Safety
+///
+/// `[lo, hi)` must lie within stack memory that is safe to read.
+#[inline(never)]
+unsafe fn first_unpainted(lo: *mut u32, hi: *mut u32) -> *mut u32 {
+    let res: *mut u32;
+    // `{addr}` truncates `{ptr}` to its low 32 bits (a no-op on RV32), matching
+    // [paint_value_for]'s `addr as u32` so this agrees with a pattern written on RV64.
+    // `{tmp}`/`{tmp2}` then recompute the address-specific pattern from [paint_value_for]
+    // for the word at `{ptr}`, so a run of live data can't be mistaken for paint just by
+    // matching a single constant. `{tmp}` is re-truncated to 32 bits afterwards, since
+    // `{addr} << k` can carry bits above bit 31 that a 32-bit rotate would have wrapped
+    // around instead, and `{value}` is truncated too since `lw` sign-extends the loaded
+    // word to the full register width on RV64 -- without both, the comparison below
+    // would only ever match by accident on RV64.
+    unsafe {
+        asm!(
+            "0:",
+            "bgeu {ptr}, {hi}, 1f",
+            "lw {value}, 0({ptr})",
+            "slli {value}, {value}, {trunc}",
+            "srli {value}, {value}, {trunc}",
+            "slli {addr}, {ptr}, {trunc}",
+            "srli {addr}, {addr}, {trunc}",
+            "slli {tmp}, {addr}, {k}",
+            "srli {tmp2}, {addr}, {krev}",
+            "or {tmp}, {tmp}, {tmp2}",
+            "xor {tmp}, {tmp}, {seed}",
+            "slli {tmp}, {tmp}, {trunc}",
+            "srli {tmp}, {tmp}, {trunc}",
+            "bne {value}, {tmp}, 1f",
+            "addi {ptr}, {ptr}, 4",
+            "j 0b",
+            "1:",
+            ptr = inout(reg) lo => res,
+            hi = in(reg) hi,
+            value = out(reg) _,
+            addr = out(reg) _,
+            tmp = out(reg) _,
+            tmp2 = out(reg) _,
+            seed = in(reg) STACK_PAINT_VALUE,
+            k = const PAINT_ROTATE,
+            krev = const 32 - PAINT_ROTATE,
+            trunc = const ADDR_TRUNC_SHIFT,
+            options(nostack, readonly)
+        )
+    };
+    res
+}
+
 /// The [Range] currently in use for the current hart's stack.
 ///
 /// Note: the stack is defined in reverse, as it runs from 'start' to 'end' downwards.
@@ -17,16 +376,25 @@ pub const STACK_PAINT_VALUE: u32 = 0xCCCC_CCCC;
 /// If you want to use this range to do range-like things, use [stack_rev] instead.
 #[inline]
 pub fn stack() -> Range<*mut u32> {
+    stack_for(hartid())
+}
+
+/// The [Range] reserved for an arbitrary hart's stack, computed from the linker symbols
+/// directly rather than from the `mhartid` CSR.
+///
+/// This is the same arithmetic [stack] uses for the current hart, but parameterized so a
+/// monitoring/telemetry task can inspect every core's stack, not just its own.
+///
+/// The same caveats as [stack] apply: `end` is one past the last valid word, and
+/// `hartid` must be a valid hart id for the platform (see [hart_count]), or the computed
+/// range may land outside the reserved stack area entirely.
+#[inline]
+pub fn stack_for(hartid: usize) -> Range<*mut u32> {
     unsafe extern "C" {
         static mut _stack_start: u32;
         static _hart_stack_size: usize;
     }
 
-    // Current hart's ID
-    let hartid: usize;
-    // SAFETY: We are just reading from a CSR
-    unsafe { asm!("csrr {}, mhartid", out(reg) hartid) };
-
     // The _hart_stack_size symbol's value, which is the size obviously,
     // is represented by the address of the symbol.
     //
@@ -59,6 +427,30 @@ pub fn stack() -> Range<*mut u32> {
     start..end
 }
 
+/// The number of harts' worth of stack space reserved by the linker script, derived from
+/// the total reserved stack span divided by [`_hart_stack_size`](stack_for).
+///
+/// A monitoring task can use this to iterate `0..hart_count()` and call [stack_for] /
+/// [stack_painted_for] on each to sum worst-case utilization across every core.
+///
+/// Unlike [stack_for], which only needs `_stack_start`/`_hart_stack_size`, this also
+/// requires the linker script to define `_stack_end` marking the very bottom of the
+/// whole multi-hart stack span (i.e. the bottom of the last hart's stack). A linker
+/// script copied from a single-hart setup won't define this symbol, and this function
+/// will fail to link until it does.
+#[inline]
+pub fn hart_count() -> usize {
+    unsafe extern "C" {
+        static _stack_start: u32;
+        static _stack_end: u32;
+        static _hart_stack_size: usize;
+    }
+
+    let total = (&raw const _stack_start as usize) - (&raw const _stack_end as usize);
+    let stksz = &raw const _hart_stack_size as usize;
+    total / stksz
+}
+
 /// The [Range] currently in use for the current hart's stack,
 /// defined in reverse such that [Range] operations are viable.
 ///
@@ -86,15 +478,29 @@ pub fn current_stack_ptr() -> *mut u32 {
 /// may differ slightly due to alignment issues.
 #[inline]
 pub fn stack_size() -> usize {
-    // SAFETY: start >= end. If this is not the case your linker did something wrong.
-    unsafe { stack().start.byte_offset_from_unsigned(stack().end) }
+    // SAFETY: `stack()` is laid out exactly as [StackRegion::new] requires, and `size`
+    // never touches `current`. Built from `stack()` rather than `stack_region()` so a
+    // guard slot reserved by [install_stack_guard] still counts as reserved space.
+    unsafe { StackRegion::new(stack()).size() }
+}
+
+/// The number of bytes reserved for an arbitrary hart's stack at compile time.
+///
+/// See [stack_for] for how the hart's range is computed.
+#[inline]
+pub fn stack_size_for(hartid: usize) -> usize {
+    // SAFETY: `stack_for(hartid)` returns a range laid out exactly as
+    // [StackRegion::new] requires; `size` never touches `current`, so there is no
+    // liveness requirement to uphold here.
+    unsafe { StackRegion::new(stack_for(hartid)).size() }
 }
 
 /// The number of bytes of the current hart's stack that are currently in use.
 #[inline]
 pub fn current_stack_in_use() -> usize {
-    // SAFETY: start >= end. If this is not the case your linker did something wrong.
-    unsafe { stack().start.byte_offset_from_unsigned(current_stack_ptr()) }
+    // SAFETY: `current_stack_ptr()` is the current hart's own live `sp`, which lies
+    // within `stack_region()`.
+    unsafe { stack_region().in_use(current_stack_ptr()) }
 }
 
 /// The number of bytes of the current hart's stack that are currently free.
@@ -102,7 +508,9 @@ pub fn current_stack_in_use() -> usize {
 /// If the stack has overflowed, this function returns 0.
 #[inline]
 pub fn current_stack_free() -> usize {
-    stack_size().saturating_sub(current_stack_in_use())
+    // SAFETY: `current_stack_ptr()` is the current hart's own live `sp`, which lies
+    // within `stack_region()`.
+    unsafe { stack_region().free(current_stack_ptr()) }
 }
 
 /// What fraction of the current hart's stack is currently in use.
@@ -119,22 +527,12 @@ pub fn current_stack_fraction() -> f32 {
 ///
 /// Runs in *O(n)* where *n* is the size of the stack.
 /// This function is inefficient in the sense that it repaints the entire stack,
-/// even the parts that still have the [STACK_PAINT_VALUE].
+/// even the parts that still hold their expected [paint_value_for] pattern.
 #[inline(never)]
 pub fn repaint_stack() {
-    // SAFETY: `stack()` has ensured we are staying within the bounds of the current hart's stack
-    unsafe {
-        asm!(
-            "0:",
-            "bgeu {ptr}, sp, 1f",
-            "sw {paint}, 0({ptr})",
-            "addi {ptr}, {ptr}, 4",
-            "j 0b",
-            "1:",
-            ptr = inout(reg) stack().end.add(1) => _,
-            paint = in(reg) STACK_PAINT_VALUE,
-        )
-    };
+    // SAFETY: `current_stack_ptr()` is the current hart's own live `sp`, and nothing
+    // else may write to its own stack concurrently.
+    unsafe { stack_region().paint(current_stack_ptr()) };
 }
 
 /// Finds the number of bytes that have not been overwritten on the current hart's stack since the last repaint.
@@ -150,33 +548,9 @@ pub fn repaint_stack() {
 /// Runs in *O(n)* where *n* is the size of the stack.
 #[inline(never)]
 pub fn stack_painted() -> usize {
-    let res: *const u32;
-    // SAFETY: As per the [rust reference], inline asm is allowed to look below the
-    // stack pointer. We read the values between the end of stack and the current stack
-    // pointer, which are all valid locations.
-    //
-    // In the case of interruption, there could be false negatives where we don't see
-    // stack that was used "behind" our cursor, however this is fine because we do not
-    // rely on this number for any safety-bearing contents, only as a metrics estimate.
-    //
-    // [rust reference]: https://doc.rust-lang.org/reference/inline-assembly.html#r-asm.rules.stack-below-sp
-    unsafe {
-        asm!(
-            "0:",
-            "bgeu {ptr}, sp, 1f",
-            "lw {value}, 0({ptr})",
-            "bne {value}, {paint}, 1f",
-            "addi {ptr}, {ptr}, 4",
-            "j 0b",
-            "1:",
-            ptr = inout(reg) stack().end.add(1) => res,
-            value = out(reg) _,
-            paint = in(reg) STACK_PAINT_VALUE,
-            options(nostack, readonly)
-        )
-    };
-    // SAFETY: res >= stack.end() because we start at stack.end()
-    unsafe { res.byte_offset_from_unsigned(stack().end) }
+    // SAFETY: `current_stack_ptr()` is the current hart's own live `sp`, which lies
+    // within `stack_region()`.
+    unsafe { stack_region().painted_linear(current_stack_ptr()) }
 }
 
 /// Finds the number of bytes that have not been overwritten on the current hart's stack since the last repaint using binary search.
@@ -189,17 +563,412 @@ pub fn stack_painted() -> usize {
 ///
 /// Runs in *O(log(n))* where *n* is the size of the stack.
 ///
-/// **Danger:** if the current (active) stack contains the [STACK_PAINT_VALUE] this computation may be very incorrect.
+/// Since each word's expected value is tied to its own address (see [paint_value_for]),
+/// a run of live data matching the pattern is astronomically unlikely, which is what
+/// makes trusting the consecutive-run assumption here safe.
 ///
 /// # Safety
 ///
 /// This function aliases the inactive stack, which is considered to be Undefined Behaviour.
 /// Do not use if you care about such things.
 pub unsafe fn stack_painted_binary() -> usize {
-    // SAFETY: we should be able to read anywhere on the stack using this,
-    // but this is considered UB because we are aliasing memory out of nowhere.
-    // Will probably still work though.
-    let slice =
-        unsafe { &*core::ptr::slice_from_raw_parts(stack().end.add(1), current_stack_free() / 4) };
-    slice.partition_point(|&word| word == STACK_PAINT_VALUE) * size_of::<usize>()
+    // SAFETY: forwarding the same aliasing caveat documented on [StackRegion::painted_binary].
+    unsafe { stack_region().painted_binary(current_stack_ptr()) }
+}
+
+/// Estimates the number of bytes that have not been overwritten on an arbitrary hart's
+/// stack since it was last painted.
+///
+/// Unlike [stack_painted], this has no live stack pointer to stop at for the target
+/// hart (it may be actively running on another core right now), so it scans the hart's
+/// whole stack range instead. Treat the result purely as an ESTIMATE of that
+/// hart's worst-case utilization, useful for a monitoring task summing usage across every
+/// core with [hart_count], and not as a precise or safety-bearing measurement.
+///
+/// Like [stack_painted], goes through the guard-aware [stack_region_for] rather than
+/// scanning [stack_for] directly, so a hart whose [install_stack_guard] has reserved its
+/// guard slot doesn't have that never-painted sentinel word mistaken for live stack data.
+///
+/// # Safety
+///
+/// This function aliases another hart's stack, which may be actively in use, and is
+/// considered to be Undefined Behaviour in the same way as [stack_painted_binary]. Do
+/// not use if you care about such things.
+pub unsafe fn stack_painted_for(hartid: usize) -> usize {
+    let region = stack_region_for(hartid);
+    // SAFETY: forwarding the same aliasing caveat documented above.
+    unsafe { region.painted_linear(region.range().start) }
+}
+
+/// The maximum number of harts supported by the per-hart tables in this crate
+/// ([OVERFLOW_HANDLERS], [GUARD_INSTALLED], [DEEPEST_SP]).
+///
+/// Not part of the public API, so a platform with more harts than this needs a fork
+/// (or a PR against this crate) that bumps this constant, not a change at the call
+/// site. Harts beyond this bound still work for plain measurement (see
+/// [stack_region_for]/[overflow_handler_for]); only the opt-in overflow-guard,
+/// overflow-handler and [repaint_stack_incremental] features are capped here (they
+/// panic on a hart at or beyond this bound).
+const MAX_HARTS: usize = 8;
+
+/// Bounds-checks `hartid` against [MAX_HARTS] and returns a raw pointer to its slot in a
+/// per-hart `[T; MAX_HARTS]` table, without ever forming a reference into the backing
+/// `static mut` (which recent toolchains reject under the `static_mut_refs` lint).
+///
+/// # Panics
+///
+/// Panics if `hartid >= MAX_HARTS`, the same bounds check `[]` indexing would have
+/// performed.
+#[inline]
+fn per_hart_slot<T>(table: *mut [T; MAX_HARTS], hartid: usize) -> *mut T {
+    assert!(
+        hartid < MAX_HARTS,
+        "hart id {hartid} is out of range for a per-hart table of size {MAX_HARTS}"
+    );
+    // SAFETY: the assert above guarantees `hartid` is in bounds, and `table` always
+    // points at a complete, properly aligned `[T; MAX_HARTS]` array per its signature.
+    unsafe { table.cast::<T>().add(hartid) }
+}
+
+/// The sentinel word [install_stack_guard] writes to the lowest valid word of the
+/// current hart's stack, used by [stack_guard_intact] to detect whether it has since
+/// been clobbered.
+pub const STACK_GUARD_SENTINEL: u32 = 0xDEAD_BEEF;
+
+/// Per-hart handler invoked by [check_stack_overflow], indexed by `mhartid`.
+///
+/// Defaults to [default_overflow_handler] on every hart.
+static mut OVERFLOW_HANDLERS: [fn(); MAX_HARTS] = [default_overflow_handler; MAX_HARTS];
+
+/// The handler [check_stack_overflow] should invoke for `hartid`.
+///
+/// Unlike [per_hart_slot], never panics: a `hartid >= `[MAX_HARTS] falls back to
+/// [default_overflow_handler], the same as a hart that never called
+/// [set_overflow_handler], since [check_stack_overflow] must stay usable for harts
+/// beyond the overflow-handler table's bound rather than crashing the one function
+/// meant to catch overflow before it corrupts memory.
+#[inline]
+fn overflow_handler_for(hartid: usize) -> fn() {
+    if hartid >= MAX_HARTS {
+        return default_overflow_handler;
+    }
+    // SAFETY: bounds-checked above.
+    unsafe { per_hart_slot(&raw mut OVERFLOW_HANDLERS, hartid).read() }
+}
+
+/// Per-hart flag tracking whether [install_stack_guard] has reserved that hart's
+/// [guard_slot] for the sentinel, indexed by `mhartid`.
+///
+/// Read by [stack_region_for] to exclude that word from painting and measurement, since
+/// [install_stack_guard] and [paint](StackRegion::paint) would otherwise fight over the
+/// same word.
+static mut GUARD_INSTALLED: [bool; MAX_HARTS] = [false; MAX_HARTS];
+
+/// Whether [install_stack_guard] has reserved `hartid`'s [guard_slot] for the sentinel.
+///
+/// Unlike [per_hart_slot], never panics: a `hartid >= `[MAX_HARTS] simply reports no
+/// guard installed, since [stack_region_for] must stay usable for harts beyond the
+/// overflow-guard table's bound (this table is sized for the opt-in guard feature, not
+/// for the hart count the underlying stack arithmetic actually supports).
+#[inline]
+fn guard_installed_for(hartid: usize) -> bool {
+    if hartid >= MAX_HARTS {
+        return false;
+    }
+    // SAFETY: bounds-checked above.
+    unsafe { per_hart_slot(&raw mut GUARD_INSTALLED, hartid).read() }
+}
+
+/// The default overflow handler: loops forever, since there is nowhere safe left to
+/// return to once a hart's stack pointer has entered its reserved margin.
+fn default_overflow_handler() {
+    loop {
+        // SAFETY: just parking the core, nothing crazy
+        unsafe { asm!("wfi", options(nomem, nostack)) };
+    }
+}
+
+/// Registers `handler` to be invoked by [check_stack_overflow] on the current hart when
+/// its stack pointer crosses into the reserved margin.
+///
+/// # Panics
+///
+/// Panics if the current hart's id is `>=` [MAX_HARTS].
+pub fn set_overflow_handler(handler: fn()) {
+    // SAFETY: each hart only ever writes to its own slot.
+    unsafe { per_hart_slot(&raw mut OVERFLOW_HANDLERS, hartid()).write(handler) };
+}
+
+/// The lowest valid word of a stack region ending at `end` (one past the last valid
+/// word, per the [stack] convention), i.e. where [install_stack_guard] writes its
+/// sentinel.
+///
+/// Pulled out on its own so the `+1` can't drift out of sync between
+/// [install_stack_guard] and [stack_guard_intact].
+#[inline]
+fn guard_slot(end: *mut u32) -> *mut u32 {
+    // SAFETY: `end` is one past the last valid word, so `end.add(1)` is the last valid
+    // word itself, provided `end` truly came from [stack].
+    unsafe { end.add(1) }
+}
+
+/// Writes [STACK_GUARD_SENTINEL] to the lowest valid word of the current hart's stack.
+///
+/// Call this once during startup, before the stack has grown anywhere near its limit.
+/// [stack_guard_intact] can then be used to detect whether the stack has since
+/// overflowed far enough to clobber the sentinel.
+///
+/// Base RISC-V has no hardware stack-limit register, so without a guard like this,
+/// overflow goes silently undetected until it corrupts a neighboring hart's stack.
+#[inline]
+pub fn install_stack_guard() {
+    // SAFETY: each hart only ever writes to its own slot.
+    unsafe { per_hart_slot(&raw mut GUARD_INSTALLED, hartid()).write(true) };
+    // SAFETY: `guard_slot(stack().end)` is the lowest valid word belonging to the
+    // current hart's stack, and is not written to by any in-bounds stack usage. Setting
+    // `GUARD_INSTALLED` above first means every subsequent call into `stack_region` (and
+    // so `repaint_stack`/`stack_painted`/...) excludes this word from now on.
+    unsafe { guard_slot(stack().end).write_volatile(STACK_GUARD_SENTINEL) };
+}
+
+/// Reads back the sentinel written by [install_stack_guard] and reports whether it is
+/// still intact.
+///
+/// Returns `false` if the sentinel has been clobbered, meaning the stack has overflowed
+/// at least as far as its very last word.
+///
+/// [install_stack_guard] must have been called first, otherwise this reads whatever
+/// happened to be left at `guard_slot(stack().end)` and the result is meaningless.
+#[inline]
+pub fn stack_guard_intact() -> bool {
+    // SAFETY: `guard_slot(stack().end)` is the lowest valid word belonging to the
+    // current hart's stack.
+    unsafe { guard_slot(stack().end).read_volatile() == STACK_GUARD_SENTINEL }
+}
+
+/// Checks whether the current hart's stack pointer has crossed into the last
+/// `margin_bytes` of its reserved stack and, if so, invokes the handler registered with
+/// [set_overflow_handler] (or [default_overflow_handler] if none was registered).
+///
+/// Meant to be called at function prologues, or periodically from a timer interrupt, as
+/// a polling-based overflow trap for cores without a hardware stack-limit register.
+///
+/// A `hartid` `>= `[MAX_HARTS] runs [default_overflow_handler] rather than panicking,
+/// same as [guard_installed_for]: this is the hot path meant to catch overflow before it
+/// corrupts memory, so the one hart-count-bound table that didn't register a handler
+/// must never be the reason detection itself crashes.
+#[inline]
+pub fn check_stack_overflow(margin_bytes: usize) {
+    // SAFETY: margin_bytes is caller-provided; if it reaches past the stack's start,
+    // every stack pointer of this hart lies within the margin, so the comparison below
+    // always trips.
+    let limit = unsafe { stack().end.byte_add(margin_bytes) };
+    if current_stack_ptr() <= limit {
+        overflow_handler_for(hartid())();
+    }
+}
+
+/// Per-hart cache of the deepest stack pointer [repaint_stack_incremental] has observed,
+/// indexed by `mhartid`.
+///
+/// A null entry means the hart has never run an incremental repaint, so there is no
+/// known boundary yet and the whole free region must be scanned once to establish one.
+static mut DEEPEST_SP: [*mut u32; MAX_HARTS] = [core::ptr::null_mut(); MAX_HARTS];
+
+/// Like [repaint_stack], but only repaints the part of the current hart's stack that has
+/// actually been used since the last call, using a per-hart cached boundary instead of
+/// rescanning the whole free region from the bottom every time.
+///
+/// The cache holds the deepest point [first_unpainted] has ever found the stack reaching
+/// (set from that scan's own result, not from `current`, so it reflects an observed
+/// high-water mark rather than wherever the stack pointer happened to return to). Each
+/// call only needs to scan and repaint between that cached boundary and `current`,
+/// instead of the whole free region, because everything below the cached boundary was
+/// confirmed painted as of the last call and nothing in this design ever writes below it
+/// except [repaint_stack] itself.
+///
+/// Runs in *O(bytes consumed since the last call)* rather than the *O(n)* of
+/// [repaint_stack] / [stack_painted], which matters for stacks repainted frequently from
+/// a tight control loop.
+///
+/// # Caveat: a deep excursion between calls can go unnoticed
+///
+/// The cached boundary can only reflect depths observed *at* a call. A transient deep
+/// recursive call that dips below the cached boundary and fully returns between two
+/// calls to this function leaves no trace in `current`, so that deeper region is left
+/// dirty and unscanned indefinitely. This cannot cause a false sense of safety:
+/// [stack_painted] and [stack_painted_binary] read real memory, so the stale (unrepainted)
+/// data there still shows up as used, which only ever makes free-space estimates *more*
+/// conservative, never less. It does mean the estimate can get stuck looking worse than
+/// reality until something repaints that region with [repaint_stack], which this function
+/// never does on its own. Call [repaint_stack] periodically (or whenever you know a rare
+/// deep excursion may have happened) if your workload can see such dips.
+#[inline(never)]
+pub fn repaint_stack_incremental() {
+    let region = stack_region();
+    let current = current_stack_ptr();
+
+    // SAFETY: each hart only ever touches its own slot.
+    let deepest = unsafe { per_hart_slot(&raw mut DEEPEST_SP, hartid()) };
+    // SAFETY: `deepest` is a valid, aligned pointer into `DEEPEST_SP`, per above.
+    let cached = unsafe { deepest.read() };
+    let known_good = if cached.is_null() {
+        // SAFETY: `region.range().end.add(1)` is the lowest valid word in the current
+        // hart's stack.
+        unsafe { region.range().end.add(1) }
+    } else {
+        cached
+    };
+
+    // SAFETY: `known_good` and `current` both lie within the current hart's stack.
+    let dirtied_from = unsafe { first_unpainted(known_good, current) };
+    // SAFETY: `[dirtied_from, current)` is part of the current hart's free stack space.
+    unsafe { paint_words(dirtied_from, current) };
+
+    // `first_unpainted` never returns an address below `known_good`, so a later scan can
+    // only find a shallower point; keeping the first boundary keeps the deepest one.
+    if cached.is_null() {
+        // SAFETY: same pointer as above, still valid and in bounds.
+        unsafe { deepest.write(dirtied_from) };
+    }
+}
+
+/// The default stride used by [probe_stack], matching the common RISC-V page size.
+pub const DEFAULT_PROBE_STRIDE_BYTES: usize = 4096;
+
+/// Walks downward from the current stack pointer in [DEFAULT_PROBE_STRIDE_BYTES]-sized
+/// strides, touching one word per stride, down to `bytes` below the current stack
+/// pointer.
+///
+/// Call this before allocating a large array or recursing deeply, passing the number of
+/// bytes the upcoming frame(s) are expected to consume. A probe whose range would reach
+/// the [overflow guard](install_stack_guard) slot (when one is installed) or go past the
+/// end of the stack touches no memory and returns `false`, so a single large frame that
+/// would land below the guard is reported rather than jumped straight past it.
+///
+/// Returns whether the probed range stayed within the current hart's stack bounds
+/// (excluding the guard slot, if installed), so callers can bail out before committing
+/// to the allocation instead of silently overflowing.
+#[inline(never)]
+pub fn probe_stack(bytes: usize) -> bool {
+    probe_stack_with_stride(bytes, DEFAULT_PROBE_STRIDE_BYTES)
+}
+
+/// Computes the lowest address [probe_stack_with_stride] would need to touch, or
+/// `None` if that address would land outside the stack region ending at `region_end`
+/// (the region's one-past-the-end address, per the [stack] convention).
+///
+/// Pulled out on its own so the bounds check can be tested without touching real stack
+/// memory: `region_end` itself is one past the last valid word, so a `target` that
+/// lands exactly on it is already out of bounds, not merely the last valid probe.
+///
+/// Compares `bytes` against the actual distance to `region_end` rather than forming
+/// `current.wrapping_byte_sub(bytes)` and comparing pointers: a `bytes` larger than
+/// `current`'s own address would wrap that subtraction around to a huge pointer that
+/// looks `>= region_end`, falsely reporting an oversized probe as in-bounds.
+#[inline]
+fn probe_target(current: *mut u32, bytes: usize, region_end: *mut u32) -> Option<*mut u32> {
+    // SAFETY: `current` lies within the stack region ending at `region_end`, so its
+    // address is `>= region_end`'s, which is what `byte_offset_from_unsigned` requires.
+    let available = unsafe { current.byte_offset_from_unsigned(region_end) };
+    // The lowest valid address is `region_end.add(1)`, i.e. `size_of::<u32>()` bytes
+    // above `region_end`, so that much must be reserved out of `available`.
+    if bytes > available.saturating_sub(size_of::<u32>()) {
+        None
+    } else {
+        Some(current.wrapping_byte_sub(bytes))
+    }
+}
+
+/// Like [probe_stack], but with a caller-chosen stride instead of
+/// [DEFAULT_PROBE_STRIDE_BYTES].
+///
+/// # Panics
+///
+/// Panics if `stride_bytes` is `0`, since a zero stride would never advance `ptr` toward
+/// `target` and this function would spin forever instead of returning.
+#[inline(never)]
+pub fn probe_stack_with_stride(bytes: usize, stride_bytes: usize) -> bool {
+    assert!(stride_bytes > 0, "stride_bytes must be greater than 0");
+
+    let region = stack_region();
+    let current = current_stack_ptr();
+    let Some(target) = probe_target(current, bytes, region.range().end) else {
+        return false;
+    };
+
+    let mut ptr = current;
+    while ptr > target {
+        ptr = ptr.wrapping_byte_sub(stride_bytes).max(target);
+        // SAFETY: `ptr` lies between `target` and `current`, both confirmed above to be
+        // within the current hart's stack bounds.
+        unsafe { core::ptr::read_volatile(ptr) };
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guard_slot_is_one_word_above_end() {
+        let end = 0x1000 as *mut u32;
+        assert_eq!(guard_slot(end), end.wrapping_add(1));
+    }
+
+    #[test]
+    fn probe_target_rejects_landing_exactly_on_region_end() {
+        let region_end = 0x1000 as *mut u32;
+        let current = 0x2000 as *mut u32;
+        // Lands exactly on region_end, which is one past the last valid word, so it
+        // must be rejected, not accepted as the last valid probe.
+        let bytes = current as usize - region_end as usize;
+        assert_eq!(probe_target(current, bytes, region_end), None);
+    }
+
+    #[test]
+    fn probe_target_accepts_the_lowest_valid_word() {
+        let region_end = 0x1000 as *mut u32;
+        let current = 0x2000 as *mut u32;
+        // Lands one word above region_end, the lowest valid word in the region.
+        let bytes = current as usize - (region_end as usize + 4);
+        assert_eq!(
+            probe_target(current, bytes, region_end),
+            Some(region_end.wrapping_add(1))
+        );
+    }
+
+    #[test]
+    fn probe_target_rejects_going_past_region_end() {
+        let region_end = 0x1000 as *mut u32;
+        let current = 0x2000 as *mut u32;
+        let bytes = current as usize - region_end as usize + 4;
+        assert_eq!(probe_target(current, bytes, region_end), None);
+    }
+
+    #[test]
+    fn probe_target_rejects_bytes_exceeding_current_address() {
+        let region_end = 0x1000 as *mut u32;
+        let current = 0x2000 as *mut u32;
+        // Larger than `current`'s own address, so `current.wrapping_byte_sub(bytes)`
+        // would wrap around to a huge pointer that looks `>= region_end` if compared
+        // directly instead of checking the distance first.
+        let bytes = current as usize + 0x1000;
+        assert_eq!(probe_target(current, bytes, region_end), None);
+    }
+
+    #[test]
+    fn paint_value_for_differs_between_adjacent_words() {
+        let a = 0x1000 as *const u32;
+        let b = 0x1004 as *const u32;
+        assert_ne!(paint_value_for(a), paint_value_for(b));
+    }
+
+    #[test]
+    fn paint_value_for_is_deterministic() {
+        let addr = 0x2000 as *const u32;
+        assert_eq!(paint_value_for(addr), paint_value_for(addr));
+    }
 }